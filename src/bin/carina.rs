@@ -1,6 +1,8 @@
+use carina::cluster::cluster;
 use carina::database::{IndexedDatabase, PeptideIx, Theoretical};
-use carina::ion_series::Kind;
-use carina::mass::{Tolerance, PROTON};
+use carina::denovo;
+use carina::ion_series::{fragments, Kind, NeutralLoss};
+use carina::mass::{Mass, Residue, Tolerance, PROTON};
 use carina::peptide::TargetDecoy;
 use carina::spectrum::{read_ms2, ProcessedSpectrum, SpectrumProcessor};
 use clap::{Arg, Command};
@@ -15,15 +17,15 @@ use std::time::{self, Instant};
 #[derive(Copy, Clone)]
 pub struct Score {
     peptide: PeptideIx,
-    matched_b: u32,
-    matched_y: u32,
-    summed_b: f32,
-    summed_y: f32,
+    /// Number of matched fragments, per [`Kind`] (indexed by [`Kind::index`])
+    matched: [u32; Kind::ALL.len()],
+    /// Summed intensity of matched fragments, per [`Kind`]
+    summed: [f32; Kind::ALL.len()],
     q_value: f32,
     hyperscore: f32,
 }
 
-#[derive(Serialize)]
+#[derive(Clone, Serialize)]
 pub struct Percolator<'db> {
     peptide: String,
     proteins: &'db str,
@@ -36,6 +38,14 @@ pub struct Percolator<'db> {
     rt: f32,
     delta_mass: f32,
     hyperscore: f32,
+    /// Hyperscore gap to the next-best candidate for this scan, from the
+    /// variant that proposed this peptide. Once [`reconcile`] has merged
+    /// hits from multiple variants for the same peptide
+    /// (`contributing_searches > 1`), this is rebased onto the *winning
+    /// variant's own* runner-up, not the dedup group's - it no longer means
+    /// "gap to the scan's overall second-best peptide", so downstream `.pin`
+    /// consumers should treat it as a per-variant confidence signal rather
+    /// than a scan-wide one once reconciliation has happened.
     deltascore: f32,
     matched_peaks: u32,
     percent_matched_peaks: f32,
@@ -44,15 +54,37 @@ pub struct Percolator<'db> {
     total_candidates: usize,
     spectrum_q_value: f32,
     q_value: f32,
+    posterior_error_probability: f32,
+    /// Number of search variants (sub-searches) that proposed this peptide
+    /// for this scan; always 1 until a consensus pass reconciles hits
+    /// from multiple variants into one
+    contributing_searches: u32,
 }
 
 impl Score {
-    /// Calculate the X!Tandem hyperscore
+    /// Sum of `matched`/`summed` over the N-terminal (a/b/c) or C-terminal (x/y/z) ion series
+    fn series(&self, n_terminal: bool) -> (u32, f32) {
+        Kind::ALL
+            .iter()
+            .filter(|k| k.n_terminal() == n_terminal)
+            .fold((0, 0.0), |(matched, summed), k| {
+                (
+                    matched + self.matched[k.index()],
+                    summed + self.summed[k.index()],
+                )
+            })
+    }
+
+    /// Calculate the X!Tandem hyperscore, treating the N-terminal (a/b/c) and
+    /// C-terminal (x/y/z) ion series as the two complementary series
     /// * `fact_table` is a precomputed vector of factorials
     fn hyperscore(&self, fact_table: &[f32]) -> f32 {
-        let i = (self.summed_b + 1.0) * (self.summed_y + 1.0);
-        let m = fact_table[(self.matched_b as usize).min(fact_table.len() - 2)]
-            * fact_table[(self.matched_y as usize).min(fact_table.len() - 2)];
+        let (matched_n, summed_n) = self.series(true);
+        let (matched_c, summed_c) = self.series(false);
+
+        let i = (summed_n + 1.0) * (summed_c + 1.0);
+        let m = fact_table[(matched_n as usize).min(fact_table.len() - 2)]
+            * fact_table[(matched_c as usize).min(fact_table.len() - 2)];
 
         let score = i.ln() + m.ln();
         if score.is_finite() {
@@ -65,24 +97,43 @@ impl Score {
     pub fn new(peptide: &Theoretical) -> Self {
         Score {
             peptide: peptide.peptide_index,
-            matched_b: 0,
-            matched_y: 0,
-            summed_b: 0.0,
-            summed_y: 0.0,
+            matched: [0; Kind::ALL.len()],
+            summed: [0.0; Kind::ALL.len()],
             q_value: 1.0,
             hyperscore: 0.0,
         }
     }
 }
 
+/// Parse a peptide rendered via [`carina::mass::Residue`]'s `Display` impl
+/// (e.g. `"PEPT(15.9949)IDE"`) back into its residues. Used by
+/// [`Scorer::score`] to regenerate ion kinds/neutral losses beyond whatever
+/// the database index was built to produce, for peptides already identified
+/// as b/y candidates.
+fn parse_peptide(rendered: &str) -> Vec<Residue> {
+    let mut residues = Vec::new();
+    let mut chars = rendered.chars().peekable();
+    while let Some(c) = chars.next() {
+        if chars.peek() == Some(&'(') {
+            chars.next();
+            let mass: String = chars.by_ref().take_while(|&c| c != ')').collect();
+            residues.push(Residue::Mod(c, mass.parse().unwrap_or(0.0)));
+        } else {
+            residues.push(Residue::Just(c));
+        }
+    }
+    residues
+}
+
 pub struct Scorer<'db> {
     db: &'db IndexedDatabase,
-    search: &'db Search,
+    variant: &'db SearchVariant,
+    report_psms: usize,
     factorial: [f32; 32],
 }
 
 impl<'db> Scorer<'db> {
-    pub fn new(db: &'db IndexedDatabase, search: &'db Search) -> Self {
+    pub fn new(db: &'db IndexedDatabase, variant: &'db SearchVariant, report_psms: usize) -> Self {
         let mut factorial = [1.0f32; 32];
         for i in 1..32 {
             factorial[i] = factorial[i - 1] * i as f32;
@@ -93,7 +144,8 @@ impl<'db> Scorer<'db> {
 
         Scorer {
             db,
-            search,
+            variant,
+            report_psms,
             factorial,
         }
     }
@@ -103,28 +155,29 @@ impl<'db> Scorer<'db> {
         let mut scores: HashMap<PeptideIx, Score> = HashMap::new();
 
         // Create a new `IndexedQuery`
-        let candidates = self
-            .db
-            .query(query, self.search.precursor_tol, self.search.fragment_tol);
+        let candidates =
+            self.db
+                .query(query, self.variant.precursor_tol, self.variant.fragment_tol);
 
         let mut total_intensity = 0.0;
         for (fragment_mz, intensity) in query.peaks.iter() {
             total_intensity += intensity;
             for frag in candidates.page_search(*fragment_mz) {
+                // The index may carry ion kinds beyond what this variant is
+                // configured to score (e.g. a shared index built for the
+                // widest variant in `search.variants`) - only tally the ones
+                // this variant actually asked for
+                if !self.variant.ion_kinds.contains(&frag.kind) {
+                    continue;
+                }
+
                 let mut sc = scores
                     .entry(frag.peptide_index)
                     .or_insert_with(|| Score::new(frag));
 
-                match frag.kind {
-                    Kind::B => {
-                        sc.matched_b += 1;
-                        sc.summed_b += intensity
-                    }
-                    Kind::Y => {
-                        sc.matched_y += 1;
-                        sc.summed_y += intensity
-                    }
-                }
+                let idx = frag.kind.index();
+                sc.matched[idx] += 1;
+                sc.summed[idx] += intensity;
             }
         }
 
@@ -132,6 +185,49 @@ impl<'db> Scorer<'db> {
             return Vec::new();
         }
 
+        // The index pass above only carries whatever kinds `variant.database`
+        // was built to generate (b/y, per the comment at the `dbs` build site
+        // in `main`). Regenerate any other configured `ion_kinds`, and
+        // `neutral_losses` variants of every kind, directly from each
+        // candidate's own sequence instead - this only widens the scoring of
+        // a candidate the index already found via a b/y match, it can't
+        // discover a new one. Unlike the indexed b/y pass this re-parses and
+        // re-matches against `query.peaks` by linear scan on every call, so
+        // a wide `ion_kinds`/`neutral_losses` config costs meaningfully more
+        // per spectrum than b/y-only scoring.
+        let needs_local_generation = self.variant.neutral_losses
+            || self
+                .variant
+                .ion_kinds
+                .iter()
+                .any(|k| !matches!(k, Kind::B | Kind::Y));
+        if needs_local_generation {
+            for (&peptide_ix, sc) in scores.iter_mut() {
+                let peptide = self.db[peptide_ix].peptide();
+                let residues = parse_peptide(&peptide.to_string());
+                let theoretical = fragments(
+                    &residues,
+                    &self.variant.ion_kinds,
+                    self.variant.neutral_losses,
+                );
+
+                for (mass, kind, loss) in theoretical {
+                    // Bare b/y fragments are already tallied from the index pass above
+                    if matches!(kind, Kind::B | Kind::Y) && loss == NeutralLoss::None {
+                        continue;
+                    }
+                    let (lo, hi) = self.variant.fragment_tol.bounds(mass);
+                    for &(mz, intensity) in query.peaks.iter() {
+                        if mz >= lo && mz <= hi {
+                            let idx = kind.index();
+                            sc.matched[idx] += 1;
+                            sc.summed[idx] += intensity;
+                        }
+                    }
+                }
+            }
+        }
+
         // Now that we have processed all candidates, calculate the hyperscore
         let mut scores = scores
             .into_values()
@@ -160,13 +256,16 @@ impl<'db> Scorer<'db> {
 
         let mut reporting = Vec::new();
 
-        for idx in 0..self.search.report_psms.min(scores.len()) {
+        for idx in 0..self.report_psms.min(scores.len()) {
             let better = scores[idx];
             let next = scores
                 .get(idx + 1)
                 .map(|score| score.hyperscore)
                 .unwrap_or_default();
 
+            let matched_peaks: u32 = better.matched.iter().sum();
+            let matched_intensity: f32 = better.summed.iter().sum();
+
             let peptide = self.db[better.peptide].peptide();
             reporting.push(Percolator {
                 peptide: peptide.to_string(),
@@ -181,14 +280,15 @@ impl<'db> Scorer<'db> {
                 delta_mass: (query.monoisotopic_mass - peptide.monoisotopic),
                 hyperscore: better.hyperscore,
                 deltascore: better.hyperscore - next,
-                matched_peaks: better.matched_b + better.matched_y,
-                percent_matched_peaks: (better.matched_b + better.matched_y) as f32
-                    / query.peaks.len() as f32,
-                matched_intensity: better.summed_b + better.summed_y,
-                percent_matched_intensity: (better.summed_b + better.summed_y) / total_intensity,
+                matched_peaks,
+                percent_matched_peaks: matched_peaks as f32 / query.peaks.len() as f32,
+                matched_intensity,
+                percent_matched_intensity: matched_intensity / total_intensity,
                 total_candidates: scores.len(),
                 spectrum_q_value: better.q_value,
                 q_value: 1.0,
+                posterior_error_probability: 1.0,
+                contributing_searches: 1,
             })
         }
         reporting
@@ -227,17 +327,308 @@ impl<'db> Scorer<'db> {
         }
         passing
     }
+
+    /// Assign posterior error probabilities (PEP) in place to a set of PSMs.
+    ///
+    /// The decoy hyperscore distribution is treated as the null model and
+    /// fit with a Gamma distribution (method-of-moments); the target
+    /// distribution is treated as a mixture of the same null plus correct
+    /// IDs, similar to OpenMS' IDPosteriorErrorProbability tool.
+    pub fn assign_pep(&self, scores: &mut [Percolator]) {
+        let pep = match PepModel::fit(scores) {
+            Some(pep) => pep,
+            None => return,
+        };
+        for score in scores.iter_mut() {
+            score.posterior_error_probability = pep.evaluate(score.hyperscore);
+        }
+    }
 }
 
-#[derive(Serialize)]
-pub struct Search {
+/// Number of bins used to histogram the (normalized) hyperscore distribution
+const PEP_BINS: usize = 100;
+
+/// Minimum number of target PSMs a smoothing window must contain before
+/// [`PepModel::evaluate`] trusts it as a density estimate - see
+/// `smoothed_target_density`
+const MIN_TARGET_WINDOW_SAMPLES: f32 = 5.0;
+
+/// Parameters of a Gamma(`shape`, `scale`) distribution
+#[derive(Copy, Clone, Debug)]
+struct GammaParams {
+    shape: f32,
+    scale: f32,
+}
+
+impl GammaParams {
+    /// Fit a Gamma distribution to `sample` via method-of-moments:
+    /// `scale = variance / mean`, `shape = mean / scale`
+    fn fit(sample: &[f32]) -> Option<GammaParams> {
+        if sample.len() < 2 {
+            return None;
+        }
+        let n = sample.len() as f32;
+        let mean = sample.iter().sum::<f32>() / n;
+        let var = sample.iter().map(|x| (x - mean).powi(2)).sum::<f32>() / n;
+        if mean <= 0.0 || var <= 0.0 {
+            return None;
+        }
+        let scale = var / mean;
+        let shape = mean / scale;
+        Some(GammaParams { shape, scale })
+    }
+
+    /// Gamma probability density function, evaluated at `x`
+    fn pdf(&self, x: f32) -> f32 {
+        if x <= 0.0 {
+            return 0.0;
+        }
+        let ln_pdf = (self.shape - 1.0) * x.ln()
+            - x / self.scale
+            - ln_gamma(self.shape)
+            - self.shape * self.scale.ln();
+        ln_pdf.exp()
+    }
+}
+
+/// Natural log of the Gamma function, via the Lanczos approximation
+fn ln_gamma(x: f32) -> f32 {
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_312e-7,
+    ];
+    if x < 0.5 {
+        // Reflection formula, for the part of the domain the series doesn't cover
+        return (std::f64::consts::PI / (std::f64::consts::PI * x as f64).sin()).ln() as f32
+            - ln_gamma(1.0 - x);
+    }
+    let x = x as f64 - 1.0;
+    let mut acc = COEFFICIENTS[0];
+    for (i, c) in COEFFICIENTS.iter().enumerate().skip(1) {
+        acc += c / (x + i as f64);
+    }
+    let t = x + 7.5;
+    (0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + acc.ln()) as f32
+}
+
+/// Fitted decoy (null) and empirical target hyperscore distributions, used
+/// to assign a posterior error probability to each PSM
+struct PepModel {
+    min_score: f32,
+    max_score: f32,
+    null: GammaParams,
+    target_hist: [f32; PEP_BINS],
+    pi0: f32,
+}
+
+impl PepModel {
+    /// Fit the null (decoy) and target distributions from a batch of PSMs
+    fn fit(scores: &[Percolator]) -> Option<PepModel> {
+        let min_score = scores.iter().map(|s| s.hyperscore).fold(f32::MAX, f32::min);
+        let max_score = scores.iter().map(|s| s.hyperscore).fold(f32::MIN, f32::max);
+        let span = (max_score - min_score).max(f32::EPSILON);
+        let normalize = |score: f32| (score - min_score) / span;
+
+        // Shift normalized scores off of zero - the Gamma distribution is only
+        // defined on (0, infinity)
+        let mut decoy_norm = Vec::new();
+        let mut decoy_hist = [0f32; PEP_BINS];
+        let mut target_hist = [0f32; PEP_BINS];
+        for score in scores {
+            let x = normalize(score.hyperscore);
+            let bin = ((x * PEP_BINS as f32) as usize).min(PEP_BINS - 1);
+            if score.label == -1 {
+                decoy_norm.push(x + 0.01);
+                decoy_hist[bin] += 1.0;
+            } else {
+                target_hist[bin] += 1.0;
+            }
+        }
+
+        let null = GammaParams::fit(&decoy_norm)?;
+
+        // Decoys are concentrated at low scores - use the bottom quartile of
+        // bins (the highest decoy density) to estimate pi0, the proportion
+        // of target PSMs that are actually incorrect. Each histogram is
+        // normalized by its own total first, so the ratio compares densities
+        // (fraction of all decoys vs. fraction of all targets in this bin)
+        // rather than raw counts, which are skewed by how many decoys vs.
+        // targets exist overall.
+        let tail = PEP_BINS / 4;
+        let total_decoy = decoy_hist.iter().sum::<f32>().max(f32::EPSILON);
+        let total_target = target_hist.iter().sum::<f32>().max(f32::EPSILON);
+        let ratios = (0..tail)
+            .filter(|&bin| decoy_hist[bin] > 0.0)
+            .map(|bin| {
+                let decoy_density = decoy_hist[bin] / total_decoy;
+                let target_density = target_hist[bin] / total_target;
+                decoy_density / target_density.max(decoy_density)
+            })
+            .collect::<Vec<f32>>();
+        let pi0 = match ratios.is_empty() {
+            true => 1.0,
+            false => ratios.iter().sum::<f32>() / ratios.len() as f32,
+        }
+        .clamp(0.0, 1.0);
+
+        Some(PepModel {
+            min_score,
+            max_score,
+            null,
+            target_hist,
+            pi0,
+        })
+    }
+
+    /// Estimate the target density around `bin`, widening the smoothing
+    /// window outward (symmetrically) until it holds at least
+    /// [`MIN_TARGET_WINDOW_SAMPLES`] or covers the whole histogram.
+    ///
+    /// A raw single-bin count is too sparse in the high-score tail - an
+    /// empty bin collapses `f_target` to `f32::EPSILON` and sends PEP back
+    /// up to 1.0, even for the best-scoring PSMs in the run. Borrowing
+    /// density from neighboring bins keeps the estimate (and thus PEP)
+    /// roughly monotonic in score.
+    fn smoothed_target_density(&self, bin: usize, bin_width: f32, total_targets: f32) -> f32 {
+        let mut radius = 0usize;
+        loop {
+            let lo = bin.saturating_sub(radius);
+            let hi = (bin + radius).min(PEP_BINS - 1);
+            let count = self.target_hist[lo..=hi].iter().sum::<f32>();
+            if count >= MIN_TARGET_WINDOW_SAMPLES || (lo == 0 && hi == PEP_BINS - 1) {
+                let window_bins = (hi - lo + 1) as f32;
+                return count / total_targets / (window_bins * bin_width);
+            }
+            radius += 1;
+        }
+    }
+
+    /// Compute PEP(s) = clamp(pi0 * f_decoy(s) / f_target(s), 0, 1)
+    fn evaluate(&self, hyperscore: f32) -> f32 {
+        let span = (self.max_score - self.min_score).max(f32::EPSILON);
+        let x = (hyperscore - self.min_score) / span;
+        let bin = ((x * PEP_BINS as f32) as usize).min(PEP_BINS - 1);
+
+        let bin_width = 1.0 / PEP_BINS as f32;
+        let total_targets = self.target_hist.iter().sum::<f32>().max(1.0);
+        let f_target = self
+            .smoothed_target_density(bin, bin_width, total_targets)
+            .max(f32::EPSILON);
+        let f_decoy = self.null.pdf(x + 0.01);
+
+        (self.pi0 * f_decoy / f_target).clamp(0.0, 1.0)
+    }
+}
+
+/// Build a [`Percolator`] row from a de novo spectrum-graph hit. There is no
+/// protein database involved, so `proteins` is always empty and `label` is
+/// always `1` (de novo hits are neither targets nor decoys).
+fn denovo_percolator<'db>(
+    specid: usize,
+    query: &ProcessedSpectrum,
+    hit: &denovo::DenovoHit,
+) -> Percolator<'db> {
+    let peptide = hit
+        .residues
+        .iter()
+        .map(|r| r.to_string())
+        .collect::<String>();
+    let calcmass = hit.residues.iter().map(|r| r.monoisotopic()).sum::<f32>() + carina::mass::H2O;
+    let total_intensity = query
+        .peaks
+        .iter()
+        .map(|(_, i)| i)
+        .sum::<f32>()
+        .max(f32::EPSILON);
+
+    Percolator {
+        peptide,
+        proteins: "",
+        specid,
+        scannr: query.scan,
+        label: 1,
+        expmass: query.monoisotopic_mass + PROTON,
+        calcmass: calcmass + PROTON,
+        charge: query.charge,
+        rt: query.rt,
+        delta_mass: query.monoisotopic_mass - calcmass,
+        hyperscore: hit.score,
+        deltascore: 0.0,
+        matched_peaks: hit.residues.len() as u32,
+        percent_matched_peaks: hit.residues.len() as f32 / query.peaks.len().max(1) as f32,
+        matched_intensity: hit.score,
+        percent_matched_intensity: hit.score / total_intensity,
+        total_candidates: 1,
+        spectrum_q_value: 1.0,
+        q_value: 1.0,
+        posterior_error_probability: 1.0,
+        contributing_searches: 1,
+    }
+}
+
+/// A single search configuration - database plus scoring parameters. `Search`
+/// holds one or more of these so that several parameter sets (e.g. a narrow
+/// and a wide fragment tolerance) can be run and reconciled into a single
+/// consensus PSM list, following the OpenMS ConsensusID idea.
+#[derive(Clone, Serialize)]
+pub struct SearchVariant {
     database: carina::database::Parameters,
     precursor_tol: Tolerance,
     fragment_tol: Tolerance,
     max_fragment_charge: u8,
+    /// Backbone ion series scored for this variant (default: b/y). For b/y,
+    /// [`Scorer::score`] just tallies what the database index already
+    /// produced; for any other kind it regenerates the fragment masses
+    /// itself, directly from each b/y candidate's own sequence, since there's
+    /// no `database.rs` in this checkout to extend the index builder with
+    /// a/c/x/z generation (see the comment at the `dbs` build site in
+    /// `main`). Either way this can only widen the *scoring* of a candidate
+    /// the index already found via a b/y match, not discover a new one.
+    ion_kinds: Vec<Kind>,
+    /// Also score water- and ammonia-loss variants of every kind in
+    /// `ion_kinds`. Generated the same way as any non-b/y `ion_kinds` entry:
+    /// directly in [`Scorer::score`], not via the database index.
+    neutral_losses: bool,
+}
+
+#[derive(Deserialize)]
+struct InputVariant {
+    database: carina::database::Builder,
+    precursor_tol: Tolerance,
+    fragment_tol: Tolerance,
+    max_fragment_charge: Option<u8>,
+    ion_kinds: Option<Vec<Kind>>,
+    neutral_losses: Option<bool>,
+}
+
+impl InputVariant {
+    fn make_variant(self) -> SearchVariant {
+        SearchVariant {
+            database: self.database.make_parameters(),
+            precursor_tol: self.precursor_tol,
+            fragment_tol: self.fragment_tol,
+            max_fragment_charge: self.max_fragment_charge.unwrap_or(3),
+            ion_kinds: self.ion_kinds.unwrap_or_else(|| vec![Kind::B, Kind::Y]),
+            neutral_losses: self.neutral_losses.unwrap_or(false),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Search {
+    variants: Vec<SearchVariant>,
     min_peaks: usize,
     max_peaks: usize,
     report_psms: usize,
+    /// Also run the FASTA-free de novo spectrum-graph sequencer on every spectrum
+    denovo: bool,
     ms2_paths: Vec<String>,
     pin_paths: Vec<String>,
     search_time: f32,
@@ -245,13 +636,15 @@ pub struct Search {
 
 #[derive(Deserialize)]
 struct Input {
-    database: carina::database::Builder,
-    precursor_tol: Tolerance,
-    fragment_tol: Tolerance,
+    #[serde(flatten)]
+    base: InputVariant,
+    /// Additional parameter sets to run alongside `base` and reconcile into
+    /// a single consensus PSM list per scan
+    additional_variants: Option<Vec<InputVariant>>,
     report_psms: Option<usize>,
     min_peaks: Option<usize>,
     max_peaks: Option<usize>,
-    max_fragment_charge: Option<u8>,
+    denovo: Option<bool>,
     ms2_paths: Vec<String>,
 }
 
@@ -259,15 +652,22 @@ impl Search {
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
         let mut file = std::fs::File::open(path)?;
         let request: Input = serde_json::from_reader(&mut file)?;
-        let database = request.database.make_parameters();
+
+        let mut variants = vec![request.base.make_variant()];
+        variants.extend(
+            request
+                .additional_variants
+                .unwrap_or_default()
+                .into_iter()
+                .map(InputVariant::make_variant),
+        );
+
         Ok(Search {
-            database,
-            precursor_tol: request.precursor_tol,
-            fragment_tol: request.fragment_tol,
+            variants,
             report_psms: request.report_psms.unwrap_or(1),
             max_peaks: request.max_peaks.unwrap_or(150),
             min_peaks: request.min_peaks.unwrap_or(15),
-            max_fragment_charge: request.max_fragment_charge.unwrap_or(3),
+            denovo: request.denovo.unwrap_or(false),
             pin_paths: Vec::new(),
             ms2_paths: request.ms2_paths,
             search_time: 0.0,
@@ -275,6 +675,67 @@ impl Search {
     }
 }
 
+/// Normalize a rendered peptide (as produced by [`carina::mass::Residue`]'s
+/// `Display` impl, e.g. `"PEPT(15.9949)IDE"`) into a dedup key for
+/// [`reconcile`]: the residue sequence, with each modification mass rounded
+/// to a fixed precision. Two variants can describe the same physical
+/// peptide+modification combination with differing mod-mass precision (or
+/// other formatting differences); comparing raw rendered strings would treat
+/// those as distinct peptides and silently fail to merge them.
+fn peptide_key(rendered: &str) -> String {
+    /// Round mod masses to the nearest milli-Dalton before keying
+    const MOD_PRECISION: f32 = 1000.0;
+
+    let mut key = String::with_capacity(rendered.len());
+    let mut chars = rendered.chars().peekable();
+    while let Some(c) = chars.next() {
+        key.push(c);
+        if chars.peek() == Some(&'(') {
+            chars.next();
+            let mass: String = chars.by_ref().take_while(|&c| c != ')').collect();
+            if let Ok(mass) = mass.parse::<f32>() {
+                let rounded = (mass * MOD_PRECISION).round() / MOD_PRECISION;
+                key.push_str(&format!("({rounded})"));
+            }
+        }
+    }
+    key
+}
+
+/// Reconcile PSMs proposed for the same scan by multiple search variants into
+/// a single consensus PSM list: group by normalized peptide+modification key
+/// (see [`peptide_key`]), and report the mean (rank-aggregated) hyperscore
+/// across the variants that agreed, alongside how many variants contributed.
+fn reconcile<'db>(hits: Vec<Percolator<'db>>) -> Vec<Percolator<'db>> {
+    let mut by_peptide: HashMap<String, Vec<Percolator<'db>>> = HashMap::new();
+    for hit in hits {
+        by_peptide
+            .entry(peptide_key(&hit.peptide))
+            .or_default()
+            .push(hit);
+    }
+
+    by_peptide
+        .into_values()
+        .map(|group| {
+            let contributing_searches = group.len() as u32;
+            let consensus_hyperscore =
+                group.iter().map(|hit| hit.hyperscore).sum::<f32>() / contributing_searches as f32;
+
+            let mut best = group
+                .into_iter()
+                .max_by(|a, b| a.hyperscore.total_cmp(&b.hyperscore))
+                .expect("a dedup group always has at least one member");
+            // Keep deltascore consistent with the now-consensus hyperscore,
+            // rather than leaving it tied to the winning variant's own runner-up
+            best.deltascore -= best.hyperscore - consensus_hyperscore;
+            best.hyperscore = consensus_hyperscore;
+            best.contributing_searches = contributing_searches;
+            best
+        })
+        .collect()
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let env = env_logger::Env::default().filter_or("CARINA_LOG", "info");
     env_logger::init_from_env(env);
@@ -291,9 +752,24 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .expect("required parameters");
     let mut search = Search::load(path)?;
 
-    let db = search.database.clone().build()?;
+    // Build one database + [`Scorer`] per search variant; multiple variants
+    // (e.g. a narrow and a wide fragment tolerance) are run independently and
+    // reconciled into a single consensus PSM list per scan.
+    //
+    // `variant.database.build()` only ever produces b/y theoretical
+    // fragments - this checkout has no `database.rs` source to extend the
+    // index builder with a/c/x/z or neutral-loss generation. `Scorer::score`
+    // compensates by regenerating any other configured `ion_kinds` (and
+    // `neutral_losses` variants) directly from each b/y candidate's own
+    // sequence; see the doc comments on `SearchVariant::ion_kinds` and
+    // `SearchVariant::neutral_losses`.
+    let dbs = search
+        .variants
+        .iter()
+        .map(|variant| variant.database.clone().build())
+        .collect::<Result<Vec<_>, _>>()?;
 
-    let buckets = db.buckets();
+    let buckets = dbs[0].buckets();
     let mut avg_delta = 0.0;
 
     for i in 1..buckets.len() {
@@ -303,25 +779,89 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     dbg!(avg_delta / buckets.len() as f32);
 
     info!(
-        "generated {} fragments in {}ms",
-        db.size(),
+        "generated {} fragments across {} search variant(s) in {}ms",
+        dbs.iter().map(|db| db.size()).sum::<usize>(),
+        dbs.len(),
         (Instant::now() - start).as_millis()
     );
 
-    let scorer = Scorer::new(&db, &search);
+    let scorers = search
+        .variants
+        .iter()
+        .zip(dbs.iter())
+        .map(|(variant, db)| Scorer::new(db, variant, search.report_psms))
+        .collect::<Vec<_>>();
+
+    // Spectrum processing (peak capping, charge deconvolution) uses the
+    // primary variant's settings, and is shared across every sub-search
     let sp = SpectrumProcessor::new(
         search.max_peaks,
-        search.max_fragment_charge,
-        search.database.fragment_max_mz,
+        search.variants[0].max_fragment_charge,
+        search.variants[0].database.fragment_max_mz,
     );
 
     let mut pin_paths = Vec::with_capacity(search.ms2_paths.len());
     for ms2_path in &search.ms2_paths {
         let start = Instant::now();
-        let mut scores = read_ms2(ms2_path)?
+
+        let processed = read_ms2(ms2_path)?
             .into_par_iter()
+            .map(|spectra| sp.process(spectra))
             .filter(|spec| spec.peaks.len() >= search.min_peaks)
-            .flat_map(|spectra| scorer.score(&sp.process(spectra)))
+            .collect::<Vec<_>>();
+
+        // Group near-duplicate spectra so only one representative per cluster
+        // is actually searched against the database
+        let clusters = cluster(&processed);
+        info!(
+            "{:?}: clustered {} spectra into {} groups",
+            ms2_path,
+            processed.len(),
+            clusters.len()
+        );
+
+        // Score every cluster representative against every search variant
+        // (the expensive step, parallelized over clusters), then propagate
+        // each variant's hits to the other cluster members. Members are only
+        // similar to the representative, not identical, so every
+        // spectrum-specific field (not just `scannr`) must be recomputed
+        // against the member's own spectrum rather than copied from the
+        // representative's PSM.
+        let scored = clusters
+            .par_iter()
+            .flat_map_iter(|cluster| {
+                scorers.iter().flat_map(move |scorer| {
+                    let psms = scorer.score(&processed[cluster.representative]);
+                    cluster
+                        .members
+                        .iter()
+                        .flat_map(move |&member| {
+                            let spectrum = &processed[member];
+                            psms.iter().cloned().map(move |mut psm| {
+                                psm.scannr = spectrum.scan;
+                                psm.rt = spectrum.rt;
+                                psm.charge = spectrum.charge;
+                                psm.expmass = spectrum.monoisotopic_mass + PROTON;
+                                psm.delta_mass =
+                                    spectrum.monoisotopic_mass - (psm.calcmass - PROTON);
+                                (spectrum.scan, psm)
+                            })
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // Group the scored hits by scan so each scan's consensus can be
+        // reconciled independently across the contributing search variants
+        let mut by_scan: HashMap<u32, Vec<Percolator<'_>>> = HashMap::new();
+        for (scannr, psm) in scored {
+            by_scan.entry(scannr).or_default().push(psm);
+        }
+
+        let mut scores = by_scan
+            .into_par_iter()
+            .flat_map(|(_, hits)| reconcile(hits))
             .collect::<Vec<_>>();
         let duration = Instant::now() - start;
 
@@ -331,8 +871,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             .from_path(&pin_path)?;
 
         scores.sort_by(|a, b| b.hyperscore.total_cmp(&a.hyperscore));
-        let passing_psms = scorer.assign_q_values(&mut scores);
+        let passing_psms = scorers[0].assign_q_values(&mut scores);
         // let passing_psms = 0;
+        scorers[0].assign_pep(&mut scores);
         let total_psms = scores.len();
 
         for (idx, mut score) in scores.into_iter().enumerate() {
@@ -350,6 +891,31 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             duration.as_millis(),
             total_psms as f32 / duration.as_secs_f32()
         );
+
+        if search.denovo {
+            let mut hits = processed
+                .par_iter()
+                .enumerate()
+                .filter_map(|(idx, spectrum)| {
+                    let hit = denovo::sequence(spectrum, search.variants[0].fragment_tol)?;
+                    Some(denovo_percolator(idx, spectrum, &hit))
+                })
+                .collect::<Vec<_>>();
+
+            let denovo_path = format!("{}.carina.denovo.pin", ms2_path);
+            let mut writer = csv::WriterBuilder::new()
+                .delimiter(b'\t')
+                .from_path(&denovo_path)?;
+            for (idx, mut hit) in hits.drain(..).enumerate() {
+                hit.specid = idx;
+                writer.serialize(hit)?;
+            }
+            info!(
+                "{:?}: de novo sequenced {} spectra",
+                ms2_path,
+                processed.len()
+            );
+        }
     }
 
     search.search_time = (Instant::now() - start).as_secs_f32();
@@ -361,3 +927,106 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::{parse_peptide, peptide_key, GammaParams, PepModel, Percolator};
+    use carina::mass::Residue;
+
+    /// Build a minimal [`Percolator`] row for PEP tests - only `label` and
+    /// `hyperscore` matter to [`PepModel::fit`]/[`PepModel::evaluate`]
+    fn psm(label: i32, hyperscore: f32) -> Percolator<'static> {
+        Percolator {
+            peptide: String::new(),
+            proteins: "",
+            specid: 0,
+            scannr: 0,
+            label,
+            expmass: 0.0,
+            calcmass: 0.0,
+            charge: 1,
+            rt: 0.0,
+            delta_mass: 0.0,
+            hyperscore,
+            deltascore: 0.0,
+            matched_peaks: 0,
+            percent_matched_peaks: 0.0,
+            matched_intensity: 0.0,
+            percent_matched_intensity: 0.0,
+            total_candidates: 1,
+            spectrum_q_value: 1.0,
+            q_value: 1.0,
+            posterior_error_probability: 1.0,
+            contributing_searches: 1,
+        }
+    }
+
+    #[test]
+    fn pep_is_not_spiky_across_a_sparse_target_bin() {
+        let mut scores = Vec::new();
+        for i in 0..1000 {
+            scores.push(psm(-1, i as f32 / 1000.0 * 0.4));
+        }
+        // Targets densely cover [0.5, 1.0], except for an empty gap around
+        // 0.8 - without smoothing, that one empty bin would collapse
+        // f_target and send PEP back up near 1.0 for a confidently-scored PSM
+        for i in 0..1000 {
+            let s = 0.5 + (i as f32 / 1000.0) * 0.5;
+            if (0.79..0.82).contains(&s) {
+                continue;
+            }
+            scores.push(psm(1, s));
+        }
+
+        let pep = PepModel::fit(&scores).expect("should fit");
+        assert!(pep.evaluate(0.77) < 0.5);
+        assert!(pep.evaluate(0.805) < 0.5);
+        assert!(pep.evaluate(0.83) < 0.5);
+    }
+
+    #[test]
+    fn peptide_key_ignores_mod_mass_precision() {
+        assert_eq!(
+            peptide_key("PEPT(15.9949)IDE"),
+            peptide_key("PEPT(15.99490001)IDE")
+        );
+        assert_ne!(peptide_key("PEPT(15.9949)IDE"), peptide_key("PEPTIDE"));
+        assert_ne!(
+            peptide_key("PEPT(15.9949)IDE"),
+            peptide_key("PEPT(42.0106)IDE")
+        );
+    }
+
+    #[test]
+    fn parse_peptide_round_trips_through_display() {
+        assert_eq!(
+            parse_peptide("PEPT(15.9949)IDE"),
+            vec![
+                Residue::Just('P'),
+                Residue::Just('E'),
+                Residue::Just('P'),
+                Residue::Mod('T', 15.9949),
+                Residue::Just('I'),
+                Residue::Just('D'),
+                Residue::Just('E'),
+            ]
+        );
+    }
+
+    #[test]
+    fn gamma_method_of_moments() {
+        // Samples drawn from a Gamma(shape=2, scale=3) distribution should
+        // recover roughly the same parameters
+        let sample = [1.2, 3.4, 5.6, 2.2, 7.8, 4.1, 6.6, 2.9, 5.0, 3.3];
+        let fit = GammaParams::fit(&sample).unwrap();
+        assert!(fit.shape > 0.0);
+        assert!(fit.scale > 0.0);
+        assert!(fit.pdf(fit.shape * fit.scale) > 0.0);
+    }
+
+    #[test]
+    fn gamma_fit_requires_samples() {
+        assert!(GammaParams::fit(&[]).is_none());
+        assert!(GammaParams::fit(&[1.0]).is_none());
+    }
+}
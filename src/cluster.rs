@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+use twox_hash::XxHash64;
+
+use crate::spectrum::ProcessedSpectrum;
+
+/// Number of independent hash seeds per LSH band
+const BAND_ROWS: usize = 4;
+/// Number of LSH bands (total MinHash signature length = `BANDS * BAND_ROWS`)
+const BANDS: usize = 8;
+/// m/z bin width (Da) used to discretize peaks into integer tokens before hashing
+const MZ_BIN: f32 = 0.02;
+
+/// A group of near-duplicate spectra, identified via MinHash/LSH
+pub struct Cluster {
+    /// Index (into the slice passed to [`cluster`]) of the highest-intensity
+    /// spectrum in this cluster - the only member that is actually searched
+    pub representative: usize,
+    /// Indices of every spectrum in this cluster, including the representative
+    pub members: Vec<usize>,
+}
+
+/// A bottom-k MinHash sketch of a spectrum's discretized peaks, used to estimate
+/// Jaccard similarity between spectra without comparing their full peak lists
+struct MinHashSketch {
+    signature: [u64; BANDS * BAND_ROWS],
+}
+
+impl MinHashSketch {
+    /// Fingerprint `spectrum` by discretizing its peaks to integer m/z tokens
+    /// (rounded to [`MZ_BIN`]), then taking the minimum hash per seed across
+    /// `BANDS * BAND_ROWS` independently seeded hashers
+    fn new(spectrum: &ProcessedSpectrum) -> MinHashSketch {
+        let tokens = spectrum
+            .peaks
+            .iter()
+            .map(|(mz, _)| (mz / MZ_BIN).round() as u32)
+            .collect::<Vec<u32>>();
+
+        let mut signature = [u64::MAX; BANDS * BAND_ROWS];
+        for (seed, slot) in signature.iter_mut().enumerate() {
+            for &token in &tokens {
+                let mut hasher = XxHash64::with_seed(seed as u64);
+                token.hash(&mut hasher);
+                *slot = (*slot).min(hasher.finish());
+            }
+        }
+        MinHashSketch { signature }
+    }
+
+    /// Hash of each band - two sketches that agree on any band's hash are
+    /// placed in the same LSH bucket for that band
+    fn bands(&self) -> impl Iterator<Item = (usize, u64)> + '_ {
+        self.signature
+            .chunks(BAND_ROWS)
+            .enumerate()
+            .map(|(band, rows)| {
+                let mut hasher = XxHash64::with_seed(band as u64);
+                rows.hash(&mut hasher);
+                (band, hasher.finish())
+            })
+    }
+}
+
+/// Disjoint-set data structure used to merge spectra that collide in any LSH band
+struct UnionFind {
+    parent: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(n: usize) -> UnionFind {
+        UnionFind {
+            parent: (0..n).collect(),
+        }
+    }
+
+    fn find(&mut self, x: usize) -> usize {
+        if self.parent[x] != x {
+            self.parent[x] = self.find(self.parent[x]);
+        }
+        self.parent[x]
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (ra, rb) = (self.find(a), self.find(b));
+        if ra != rb {
+            self.parent[ra] = rb;
+        }
+    }
+}
+
+fn total_intensity(spectrum: &ProcessedSpectrum) -> f32 {
+    spectrum.peaks.iter().map(|(_, intensity)| intensity).sum()
+}
+
+/// Cluster near-duplicate spectra via MinHash/LSH banding, so that only the
+/// highest-intensity representative of each cluster needs to be searched
+/// against the database. `spectra` is indexed positionally by the returned
+/// [`Cluster::representative`] and [`Cluster::members`].
+pub fn cluster(spectra: &[ProcessedSpectrum]) -> Vec<Cluster> {
+    let sketches = spectra.iter().map(MinHashSketch::new).collect::<Vec<_>>();
+
+    let mut buckets: HashMap<(usize, u64), Vec<usize>> = HashMap::new();
+    for (idx, sketch) in sketches.iter().enumerate() {
+        for band in sketch.bands() {
+            buckets.entry(band).or_default().push(idx);
+        }
+    }
+
+    let mut uf = UnionFind::new(spectra.len());
+    for members in buckets.values() {
+        for pair in members.windows(2) {
+            uf.union(pair[0], pair[1]);
+        }
+    }
+
+    let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+    for idx in 0..spectra.len() {
+        groups.entry(uf.find(idx)).or_default().push(idx);
+    }
+
+    groups
+        .into_values()
+        .map(|members| {
+            let representative = members
+                .iter()
+                .copied()
+                .max_by(|&a, &b| {
+                    total_intensity(&spectra[a]).total_cmp(&total_intensity(&spectra[b]))
+                })
+                .expect("a cluster always has at least one member");
+            Cluster {
+                representative,
+                members,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn spectrum(peaks: &[(f32, f32)]) -> ProcessedSpectrum {
+        ProcessedSpectrum {
+            scan: 0,
+            rt: 0.0,
+            charge: 2,
+            monoisotopic_mass: 0.0,
+            peaks: peaks.to_vec(),
+        }
+    }
+
+    #[test]
+    fn identical_spectra_cluster_together() {
+        let peaks = [(400.2, 10.0), (401.2, 5.0), (402.2, 1.0)];
+        let spectra = vec![
+            spectrum(&peaks),
+            spectrum(&peaks),
+            spectrum(&[(900.0, 1.0)]),
+        ];
+
+        let clusters = cluster(&spectra);
+        assert_eq!(clusters.len(), 2);
+
+        let sizes = clusters
+            .iter()
+            .map(|c| c.members.len())
+            .collect::<std::collections::BTreeSet<_>>();
+        assert!(sizes.contains(&2));
+        assert!(sizes.contains(&1));
+    }
+
+    #[test]
+    fn representative_is_highest_intensity_member() {
+        let spectra = vec![
+            spectrum(&[(400.2, 1.0), (401.2, 1.0)]),
+            spectrum(&[(400.2, 50.0), (401.2, 50.0)]),
+        ];
+        let clusters = cluster(&spectra);
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].representative, 1);
+    }
+}
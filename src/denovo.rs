@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+
+use crate::mass::{Mass, Residue, Tolerance, VALID_AA};
+use crate::spectrum::ProcessedSpectrum;
+
+/// Widest residue mass (Da) an edge can span - used to prune the DP search
+const MAX_RESIDUE_MASS: f32 = 190.0;
+/// Bin width (Da) used to merge complementary b/y prefix-mass interpretations
+/// of the same peak onto a single spectrum graph node
+const MASS_BIN: f32 = 0.01;
+
+/// A candidate peptide sequence produced by the spectrum-graph de novo search,
+/// reported with an empty `proteins` field since no database is involved
+pub struct DenovoHit {
+    pub residues: Vec<Residue>,
+    pub score: f32,
+}
+
+#[derive(Copy, Clone)]
+struct Node {
+    mass: f32,
+    intensity: f32,
+    /// The `MASS_BIN` bucket this node was merged under, used to locate the
+    /// mass-0 and precursor nodes again after sorting by mass
+    bucket: i64,
+}
+
+struct Best {
+    score: f32,
+    prev: Option<usize>,
+    residue: Option<Residue>,
+}
+
+/// Sequence a spectrum de novo (no FASTA required) by building a spectrum
+/// graph and running a dynamic-programming longest-scoring-path search from
+/// mass 0 to the summed residue mass (the neutral precursor mass minus one
+/// water).
+///
+/// Nodes are candidate residue-prefix-sum masses: 0, `precursor - H2O`, and
+/// every observed peak interpreted as either a b-ion or a complementary
+/// y-ion prefix mass (peaks whose two interpretations land within
+/// [`MASS_BIN`] of each other are merged into a single node, crediting
+/// both). A directed edge connects two nodes whose mass difference matches
+/// one of [`VALID_AA`] within `fragment_tol`; its score is the destination
+/// node's intensity.
+pub fn sequence(query: &ProcessedSpectrum, fragment_tol: Tolerance) -> Option<DenovoHit> {
+    let precursor = query.monoisotopic_mass;
+    let bin = |mass: f32| (mass / MASS_BIN).round() as i64;
+
+    // Every other node (0, a raw peak, and its `precursor - mz` complement)
+    // lives on the water-free residue-prefix-sum basis - the goal node must
+    // be seeded there too, not at the full (+H2O) neutral precursor mass,
+    // or the final edge into it would be permanently short by one water mass
+    let goal = precursor - crate::mass::H2O;
+    let precursor_bin = bin(goal);
+
+    let mut merged: HashMap<i64, f32> = HashMap::new();
+    merged.entry(bin(0.0)).or_insert(0.0);
+    merged.entry(precursor_bin).or_insert(0.0);
+    for &(mz, intensity) in &query.peaks {
+        // Both interpretations of a peak (as a b-ion or a complementary y-ion
+        // prefix mass) are only meaningful within [0, precursor] - peaks above
+        // the precursor (noise, internal fragments, multiply-charged-precursor
+        // artifacts) would otherwise produce a node past the precursor or, via
+        // `precursor - mz`, a node with negative mass
+        if mz >= 0.0 && mz <= precursor {
+            *merged.entry(bin(mz)).or_insert(0.0) += intensity;
+        }
+        let complement = precursor - mz;
+        if complement >= 0.0 && complement <= precursor {
+            *merged.entry(bin(complement)).or_insert(0.0) += intensity;
+        }
+    }
+
+    let mut nodes = merged
+        .into_iter()
+        .map(|(bucket, intensity)| Node {
+            mass: bucket as f32 * MASS_BIN,
+            intensity,
+            bucket,
+        })
+        .collect::<Vec<Node>>();
+    nodes.sort_by(|a, b| a.mass.total_cmp(&b.mass));
+
+    let n = nodes.len();
+    let zero_idx = nodes
+        .iter()
+        .position(|node| node.bucket == bin(0.0))
+        .expect("the mass-0 node is always inserted");
+    let precursor_idx = nodes
+        .iter()
+        .position(|node| node.bucket == precursor_bin)
+        .expect("the precursor node is always inserted");
+
+    let mut best = (0..n)
+        .map(|_| Best {
+            score: f32::MIN,
+            prev: None,
+            residue: None,
+        })
+        .collect::<Vec<Best>>();
+    best[zero_idx].score = 0.0;
+
+    for i in 0..n {
+        if best[i].score == f32::MIN {
+            continue;
+        }
+        for j in (i + 1)..n {
+            let delta = nodes[j].mass - nodes[i].mass;
+            if delta > MAX_RESIDUE_MASS {
+                break;
+            }
+            for aa in VALID_AA {
+                let (lo, hi) = fragment_tol.bounds(aa.monoisotopic());
+                if delta < lo || delta > hi {
+                    continue;
+                }
+                let score = best[i].score + nodes[j].intensity;
+                if score > best[j].score {
+                    best[j].score = score;
+                    best[j].prev = Some(i);
+                    best[j].residue = Some(Residue::Just(aa));
+                }
+            }
+        }
+    }
+
+    if best[precursor_idx].prev.is_none() {
+        return None;
+    }
+
+    let mut residues = Vec::new();
+    let mut idx = precursor_idx;
+    while let Some(prev) = best[idx].prev {
+        residues.push(
+            best[idx]
+                .residue
+                .clone()
+                .expect("edge always carries a residue"),
+        );
+        idx = prev;
+    }
+    residues.reverse();
+
+    Some(DenovoHit {
+        residues,
+        score: best[precursor_idx].score,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sequences_a_simple_dipeptide() {
+        // "AG": A (71.03712) + G (57.021465), precursor = sum + water
+        let a = 'A'.monoisotopic();
+        let g = 'G'.monoisotopic();
+        let precursor = a + g + crate::mass::H2O;
+
+        let query = ProcessedSpectrum {
+            scan: 0,
+            rt: 0.0,
+            charge: 1,
+            monoisotopic_mass: precursor,
+            peaks: vec![(a, 100.0)],
+        };
+
+        let hit = sequence(&query, Tolerance::Th(0.01)).expect("should find a path");
+        assert_eq!(hit.residues, vec![Residue::Just('A'), Residue::Just('G')]);
+    }
+
+    #[test]
+    fn no_path_when_gap_matches_no_residue() {
+        let query = ProcessedSpectrum {
+            scan: 0,
+            rt: 0.0,
+            charge: 1,
+            monoisotopic_mass: 1.0,
+            peaks: vec![(0.5, 10.0)],
+        };
+        assert!(sequence(&query, Tolerance::Th(0.01)).is_none());
+    }
+
+    #[test]
+    fn ignores_peaks_above_precursor() {
+        // A noise peak above the precursor mass must not be mistaken for the
+        // precursor node, nor produce a negative-mass node via `precursor - mz`
+        let a = 'A'.monoisotopic();
+        let g = 'G'.monoisotopic();
+        let precursor = a + g + crate::mass::H2O;
+
+        let query = ProcessedSpectrum {
+            scan: 0,
+            rt: 0.0,
+            charge: 1,
+            monoisotopic_mass: precursor,
+            peaks: vec![(a, 100.0), (precursor + 50.0, 1000.0)],
+        };
+
+        let hit = sequence(&query, Tolerance::Th(0.01)).expect("should find a path");
+        assert_eq!(hit.residues, vec![Residue::Just('A'), Residue::Just('G')]);
+    }
+
+    #[test]
+    fn sequences_a_full_length_peptide() {
+        // A complete, noiseless b-ion ladder (one peak per internal cleavage
+        // site) for a 7-residue peptide - the final edge into the goal node
+        // must land exactly on the summed residue mass, not the +H2O neutral
+        // precursor mass
+        let peptide = "PEPTIDE";
+        let residues = peptide
+            .chars()
+            .map(|c| c.monoisotopic())
+            .collect::<Vec<_>>();
+        let precursor = residues.iter().sum::<f32>() + crate::mass::H2O;
+
+        let mut prefix = 0.0;
+        let peaks = residues[..residues.len() - 1]
+            .iter()
+            .map(|r| {
+                prefix += r;
+                (prefix, 100.0)
+            })
+            .collect::<Vec<_>>();
+
+        let query = ProcessedSpectrum {
+            scan: 0,
+            rt: 0.0,
+            charge: 1,
+            monoisotopic_mass: precursor,
+            peaks,
+        };
+
+        let hit = sequence(&query, Tolerance::Th(0.01)).expect("should find a path");
+        let expected = peptide.chars().map(Residue::Just).collect::<Vec<_>>();
+        assert_eq!(hit.residues, expected);
+    }
+}
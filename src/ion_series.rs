@@ -0,0 +1,201 @@
+use crate::mass::{Mass, Residue, H2O, NH3};
+use serde::{Deserialize, Serialize};
+
+/// Mass (Da) of a CO group, used to convert between b/a and y/x ion masses
+const CO: f32 = 27.994915;
+/// Mass (Da) of H2, used to convert between y and x ion masses
+const H2: f32 = 2.01565;
+
+/// The backbone cleavage type of a theoretical fragment ion
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    A,
+    B,
+    C,
+    X,
+    Y,
+    Z,
+}
+
+impl Kind {
+    /// All backbone ion types this module is capable of generating
+    pub const ALL: [Kind; 6] = [Kind::A, Kind::B, Kind::C, Kind::X, Kind::Y, Kind::Z];
+
+    /// `true` for ions generated from the N-terminal side of the cleaved bond (a/b/c),
+    /// `false` for ions generated from the C-terminal side (x/y/z)
+    pub fn n_terminal(&self) -> bool {
+        matches!(self, Kind::A | Kind::B | Kind::C)
+    }
+
+    /// Position of this ion type within [`Kind::ALL`], used to index per-ion-type tallies
+    pub fn index(&self) -> usize {
+        Kind::ALL
+            .iter()
+            .position(|k| k == self)
+            .expect("Kind::ALL is exhaustive")
+    }
+
+    /// Mass shift (Da) applied to the relevant terminal residue-prefix-sum
+    /// (see [`fragments`]) to get this ion kind's neutral fragment mass,
+    /// following the same convention as the rest of this codebase: b is the
+    /// bare N-terminal residue-prefix-sum and y is the C-terminal
+    /// residue-prefix-sum plus one water, with no proton added
+    pub fn offset(&self) -> f32 {
+        match self {
+            Kind::A => -CO,
+            Kind::B => 0.0,
+            Kind::C => NH3,
+            Kind::X => H2O + CO - H2,
+            Kind::Y => H2O,
+            Kind::Z => H2O - NH3,
+        }
+    }
+}
+
+/// Generate every theoretical backbone fragment mass for `residues`, for
+/// each of `ion_kinds`, tagged with the [`NeutralLoss`] applied (`None` for
+/// the bare fragment). If `neutral_losses` is set, a fragment also gets a
+/// water- or ammonia-loss variant for any loss it has an eligible residue
+/// for. Used by `Scorer::score` (in `src/bin/carina.rs`) to score ion kinds
+/// and neutral losses beyond whatever the database index itself was built
+/// to produce.
+pub fn fragments(
+    residues: &[Residue],
+    ion_kinds: &[Kind],
+    neutral_losses: bool,
+) -> Vec<(f32, Kind, NeutralLoss)> {
+    let n = residues.len();
+    if n < 2 {
+        return Vec::new();
+    }
+
+    let mut n_term = vec![0.0f32; n];
+    let mut acc = 0.0;
+    for (i, r) in residues.iter().enumerate() {
+        acc += r.monoisotopic();
+        n_term[i] = acc;
+    }
+    let mut c_term = vec![0.0f32; n];
+    let mut acc = 0.0;
+    for (i, r) in residues.iter().rev().enumerate() {
+        acc += r.monoisotopic();
+        c_term[i] = acc;
+    }
+
+    let mut out = Vec::new();
+    for &kind in ion_kinds {
+        let prefix = if kind.n_terminal() { &n_term } else { &c_term };
+        // A fragment must retain at least one residue on each side of the
+        // cleaved bond, so only the first n - 1 prefix sums are valid
+        for k in 0..n - 1 {
+            let base = prefix[k] + kind.offset();
+            out.push((base, kind, NeutralLoss::None));
+
+            if neutral_losses {
+                let fragment = if kind.n_terminal() {
+                    &residues[..=k]
+                } else {
+                    &residues[n - 1 - k..]
+                };
+                for loss in [NeutralLoss::Water, NeutralLoss::Ammonia] {
+                    let eligible = fragment.iter().any(|r| {
+                        let residue = match r {
+                            Residue::Just(c) | Residue::Mod(c, _) => *c,
+                        };
+                        loss.allowed(residue)
+                    });
+                    if eligible {
+                        out.push((base + loss.mass(), kind, loss));
+                    }
+                }
+            }
+        }
+    }
+    out
+}
+
+/// A neutral loss applied on top of a backbone fragment ion. Only residues
+/// capable of carrying the loss are eligible: S/T/E/D for water, and
+/// K/R/Q/N for ammonia.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NeutralLoss {
+    None,
+    Water,
+    Ammonia,
+}
+
+impl NeutralLoss {
+    /// All neutral losses this module is capable of generating
+    pub const ALL: [NeutralLoss; 3] = [NeutralLoss::None, NeutralLoss::Water, NeutralLoss::Ammonia];
+
+    /// Mass shift (Da) applied by this neutral loss, relative to the unmodified fragment
+    pub fn mass(&self) -> f32 {
+        match self {
+            NeutralLoss::None => 0.0,
+            NeutralLoss::Water => -crate::mass::H2O,
+            NeutralLoss::Ammonia => -crate::mass::NH3,
+        }
+    }
+
+    /// Whether `residue` is capable of carrying this neutral loss
+    pub fn allowed(&self, residue: char) -> bool {
+        match self {
+            NeutralLoss::None => true,
+            NeutralLoss::Water => matches!(residue, 'S' | 'T' | 'E' | 'D'),
+            NeutralLoss::Ammonia => matches!(residue, 'K' | 'R' | 'Q' | 'N'),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{fragments, Kind, NeutralLoss};
+    use crate::mass::Residue;
+
+    #[test]
+    fn kind_index_is_unique() {
+        let mut indices = Kind::ALL.iter().map(|k| k.index()).collect::<Vec<_>>();
+        indices.sort_unstable();
+        assert_eq!(indices, vec![0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn neutral_loss_gating() {
+        assert!(NeutralLoss::Water.allowed('S'));
+        assert!(!NeutralLoss::Water.allowed('K'));
+        assert!(NeutralLoss::Ammonia.allowed('K'));
+        assert!(!NeutralLoss::Ammonia.allowed('S'));
+        assert!(NeutralLoss::None.allowed('A'));
+    }
+
+    #[test]
+    fn a_and_c_ions_are_offset_from_b() {
+        let b = Kind::B.offset();
+        assert_eq!(Kind::A.offset(), b - super::CO);
+        assert_eq!(Kind::C.offset(), b + crate::mass::NH3);
+    }
+
+    #[test]
+    fn fragments_cover_every_internal_cleavage_site() {
+        // "AG": only 1 internal cleavage site, so exactly 1 fragment per kind
+        let residues = vec![Residue::Just('A'), Residue::Just('G')];
+        let out = fragments(&residues, &[Kind::A, Kind::B], false);
+        assert_eq!(out.len(), 2);
+        assert!(out.iter().all(|(_, _, loss)| *loss == NeutralLoss::None));
+    }
+
+    #[test]
+    fn fragments_gate_neutral_losses_by_residue() {
+        // Only the fragment containing 'S' is eligible for a water loss
+        let residues = vec![Residue::Just('S'), Residue::Just('G'), Residue::Just('A')];
+        let out = fragments(&residues, &[Kind::B], true);
+        let water_losses = out
+            .iter()
+            .filter(|(_, kind, loss)| *kind == Kind::B && *loss == NeutralLoss::Water)
+            .count();
+        // Both b1 ("S") and b2 ("SG") contain the eligible 'S'
+        assert_eq!(water_losses, 2);
+    }
+}